@@ -1,26 +1,39 @@
-use std::{io::ErrorKind, path::PathBuf, process::Command, time::Duration};
+use std::{
+    borrow::Cow,
+    io::ErrorKind,
+    marker::PhantomData,
+    path::PathBuf,
+    process::Command,
+    time::{Duration, Instant},
+};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use libafl::{
-    corpus::{CachedOnDiskCorpus, OnDiskCorpus},
-    events::{ClientDescription, EventConfig, Launcher},
-    executors::ForkserverExecutor,
-    feedback_or,
-    feedbacks::{CrashFeedback, MaxMapFeedback, TimeFeedback},
-    inputs::{BytesInput, GeneralizedInputMetadata},
-    monitors::MultiMonitor,
+    corpus::{CachedOnDiskCorpus, Corpus, OnDiskCorpus, Testcase},
+    events::{ClientDescription, Event, EventConfig, EventFirer, Launcher, NopEventManager},
+    executors::{Executor, ExitKind, ForkserverExecutor, HasObservers},
+    feedback_and_fast, feedback_or, feedback_or_fast,
+    feedbacks::{
+        CrashFeedback, Feedback, MaxMapFeedback, NewHashFeedback, StateInitializer, TimeFeedback,
+        TimeoutFeedback,
+    },
+    inputs::{BytesInput, GeneralizedInputMetadata, Input},
+    monitors::{tui::TuiMonitor, AggregatorOps, MultiMonitor, UserStats, UserStatsValue},
     mutators::{
         havoc_mutations, tokens_mutations, AFLppRedQueen, GrimoireExtensionMutator,
         GrimoireRandomDeleteMutator, GrimoireRecursiveReplacementMutator,
-        GrimoireStringReplacementMutator, HavocScheduledMutator, Tokens,
+        GrimoireStringReplacementMutator, HavocScheduledMutator, StdMOptMutator, Tokens,
+    },
+    observers::{
+        AsanBacktraceObserver, CanTrack, HitcountsMapObserver, MapObserver, StdMapObserver,
+        TimeObserver,
     },
-    observers::{CanTrack, HitcountsMapObserver, StdMapObserver, TimeObserver},
     schedulers::{powersched::PowerSchedule, StdWeightedScheduler},
     stages::{
-        mutational::MultiMutationalStage, ColorizationStage, GeneralizationStage, IfStage,
-        StdMutationalStage, SyncFromDiskStage,
+        mutational::MultiMutationalStage, CalibrationStage, ColorizationStage, GeneralizationStage,
+        IfStage, StdMutationalStage, SyncFromDiskStage,
     },
-    state::{HasCurrentTestcase, StdState},
+    state::{HasCurrentTestcase, HasNamedMetadata, StdState},
     Error, Fuzzer, HasMetadata, StdFuzzer,
 };
 use libafl_bolts::{
@@ -28,16 +41,335 @@ use libafl_bolts::{
     current_nanos,
     ownedref::OwnedRefMut,
     rands::{RomuDuoJrRand, StdRand},
-    shmem::{ShMem, ShMemProvider, StdShMemProvider, UnixShMemProvider},
-    tuples::{tuple_list, Handled, Merge},
-    AsSliceMut, TargetArgs,
+    shmem::{ShMem, ShMemProvider, StdShMemProvider, UnixShMem, UnixShMemProvider},
+    tuples::{tuple_list, Handle, Handled, MatchName, Merge},
+    AsSliceMut, Named, TargetArgs,
 };
+use libafl_nyx::{NyxExecutor, NyxHelperBuilder};
 use libafl_targets::{cmps::AFLppCmpLogMap, AFLppCmpLogObserver, AFLppCmplogTracingStage};
 const SHMEM_ENV_VAR: &str = "__AFL_SHM_ID";
+
+/// Archives timed-out inputs into their own on-disk corpus instead of letting
+/// them fall into the crash corpus, so slow-path/DoS bugs can be triaged
+/// independently of real crashes. Still reports a `hangs` user stat on every
+/// archive so the count is visible on the monitor/TUI even though the hang
+/// never becomes an objective in the shared solutions corpus.
+struct HangArchiveFeedback {
+    timeout: TimeoutFeedback,
+    hangs: OnDiskCorpus<BytesInput>,
+    hang_count: u64,
+}
+
+impl HangArchiveFeedback {
+    fn new(hangs: OnDiskCorpus<BytesInput>) -> Self {
+        Self {
+            timeout: TimeoutFeedback::new(),
+            hangs,
+            hang_count: 0,
+        }
+    }
+}
+
+impl Named for HangArchiveFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("HangArchiveFeedback");
+        &NAME
+    }
+}
+
+impl<S> StateInitializer<S> for HangArchiveFeedback {}
+
+impl<EM, OT, S> Feedback<EM, BytesInput, OT, S> for HangArchiveFeedback
+where
+    EM: EventFirer<BytesInput, S>,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        input: &BytesInput,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        if self
+            .timeout
+            .is_interesting(state, manager, input, observers, exit_kind)?
+        {
+            self.hangs.add(Testcase::new(input.clone()))?;
+            self.hang_count += 1;
+            manager.fire(
+                state,
+                Event::UpdateUserStats {
+                    name: Cow::Borrowed("hangs"),
+                    value: UserStats::new(
+                        UserStatsValue::Number(self.hang_count),
+                        AggregatorOps::Sum,
+                    ),
+                    phantom: PhantomData,
+                },
+            )?;
+        }
+        // Never contribute to the shared crash solutions corpus; hangs are
+        // archived in their own corpus and surfaced via the `hangs` stat above.
+        Ok(false)
+    }
+}
+
+/// Dedupes crashes by `NewHashFeedback` on the parsed ASAN/LLVM backtrace,
+/// except a crash whose trace didn't parse (no `hash()`) always counts as
+/// interesting — we'd rather keep a handful of duplicate "unknown" crashes
+/// than silently drop one we can't fingerprint.
+struct CrashDedupFeedback {
+    hash: NewHashFeedback<AsanBacktraceObserver>,
+    handle: Handle<AsanBacktraceObserver>,
+}
+
+impl CrashDedupFeedback {
+    fn new(observer: &AsanBacktraceObserver) -> Self {
+        Self {
+            hash: NewHashFeedback::new(observer),
+            handle: observer.handle(),
+        }
+    }
+}
+
+impl Named for CrashDedupFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("CrashDedupFeedback");
+        &NAME
+    }
+}
+
+impl<S: HasNamedMetadata> StateInitializer<S> for CrashDedupFeedback {
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        self.hash.init_state(state)
+    }
+}
+
+impl<EM, OT, S> Feedback<EM, BytesInput, OT, S> for CrashDedupFeedback
+where
+    OT: MatchName,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        input: &BytesInput,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let parsed = observers
+            .get(&self.handle)
+            .is_some_and(|observer| observer.hash().is_some());
+        if !parsed {
+            return Ok(true);
+        }
+        self.hash
+            .is_interesting(state, manager, input, observers, exit_kind)
+    }
+}
+
+/// Probes the target's `AFL_DUMP_MAP_SIZE` to size the shared coverage map.
+/// Every forkserver mode (fuzzing or `triage`) needs this, so it's shared
+/// instead of re-run per call site.
+fn probe_map_size(opt: &Opt) -> usize {
+    let output = Command::new(opt.executable.clone())
+        .env("AFL_DUMP_MAP_SIZE", "1")
+        .output()
+        .expect("target gave no output");
+    let map_size = String::from_utf8(output.stdout)
+        .expect("target returned illegal mapsize")
+        .replace("\n", "");
+    map_size.parse::<usize>().expect("illegal mapsize output") + opt.map_bias
+}
+
+/// Allocates the shared coverage map and hands the forkserver its shmem id.
+/// The other half of forkserver bring-up shared between `triage` and the
+/// non-Nyx `run_client` path.
+fn new_coverage_shmem(shmem_provider: &mut UnixShMemProvider, map_size: usize) -> UnixShMem {
+    let mut shmem = shmem_provider.new_shmem(map_size).unwrap();
+    unsafe {
+        shmem.write_to_env(SHMEM_ENV_VAR).unwrap();
+    }
+    shmem
+}
+
+/// Replays a single saved input through a fresh forkserver executor, outside
+/// the fuzzing loop, with the same coverage map and hitcounts edges observer
+/// as the forkserver modes of `run_client` plus the backtrace observer of
+/// `--dedup-crashes`; no time observer is attached. Used by `triage` to
+/// explain what a saved crash or hang
+/// actually does without re-running the whole campaign.
+fn run_triage(opt: &Opt, file: PathBuf) -> Result<(), Error> {
+    let map_size = probe_map_size(opt);
+    let mut shmem_provider = UnixShMemProvider::new().unwrap();
+    let mut shmem = new_coverage_shmem(&mut shmem_provider, map_size);
+    let shmem_buf = shmem.as_slice_mut();
+    let edges_observer = unsafe {
+        HitcountsMapObserver::new(StdMapObserver::new("edges", shmem_buf))
+            .track_indices()
+            .track_novelties()
+    };
+    // Parses the ASAN/LLVM sanitizer report from the child's stderr, same as
+    // the `--dedup-crashes` path, so triage prints the same frames the
+    // dedup hash is computed from.
+    let backtrace_observer = AsanBacktraceObserver::default();
+    let mut feedback = MaxMapFeedback::new(&edges_observer);
+    let mut objective = CrashFeedback::new();
+    let mut state = StdState::new(
+        StdRand::with_seed(current_nanos()),
+        CachedOnDiskCorpus::<BytesInput>::new(opt.out_dir.join("queue"), 1000).unwrap(),
+        OnDiskCorpus::<BytesInput>::new(opt.out_dir.join("crashes")).unwrap(),
+        &mut feedback,
+        &mut objective,
+    )
+    .unwrap();
+    let scheduler = StdWeightedScheduler::with_schedule(
+        &mut state,
+        &edges_observer,
+        Some(PowerSchedule::explore()),
+    );
+    let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
+    let mut mgr = NopEventManager::new();
+    let mut executor = ForkserverExecutor::builder()
+        .program(opt.executable.clone())
+        .coverage_map_size(map_size)
+        // Must stay off: with it on, the child's stderr is inherited by the
+        // terminal instead of captured, so `backtrace_observer` never sees
+        // the ASAN/LLVM report and the `backtrace:` line below prints empty.
+        .debug_child(false)
+        .is_persistent(true)
+        .is_deferred_frksrv(true)
+        .timeout(Duration::from_millis(opt.hang_timeout * 1000))
+        .shmem_provider(&mut shmem_provider)
+        .build_dynamic_map(edges_observer, tuple_list!(backtrace_observer))
+        .unwrap();
+    let input = BytesInput::from_file(&file)?;
+    let start = Instant::now();
+    let exit_kind = executor.run_target(&mut fuzzer, &mut state, &mut mgr, &input)?;
+    let elapsed = start.elapsed();
+    let observers = executor.observers();
+    let covered_edges = observers.0.count_bytes();
+    let backtrace = &observers.1 .0;
+    println!("file:          {}", file.display());
+    println!("exit kind:     {exit_kind:?}");
+    println!("time:          {elapsed:?}");
+    println!("covered edges: {covered_edges}");
+    println!("backtrace:     {backtrace:?}");
+    Ok(())
+}
+
+/// Builds the CmpLog/RedQueen pipeline (its own forkserver + shared CmpLog
+/// map, traced on first scheduling per testcase on the main node), the
+/// foreign-corpus sync stage, the main forkserver executor, loads the
+/// initial corpus and runs the fuzz loop. `--dedup-crashes`, `--mopt` and
+/// the default forkserver path only differ in the mutator, the extra
+/// observers and `debug_child`, so this is a `macro_rules!` rather than a plain fn:
+/// the fuzzer/state/mutator types differ at every call site (they're
+/// monomorphized over the objective feedback and mutator in play), and a
+/// generic fn would need to name all of that instead of letting each call
+/// site infer its own types the way the rest of this file already does.
+///
+/// `debug_child` is a parameter rather than hardcoded: with it on, the
+/// forkserver inherits the child's stdout/stderr straight to the terminal
+/// instead of capturing it, so `AsanBacktraceObserver` (when present in
+/// `extra_objective_observers`) never sees the ASAN/LLVM report and
+/// `hash()` stays `None`. `--dedup-crashes` must pass `false` unconditionally
+/// for that reason; branches without a backtrace observer are free to key
+/// it off `--tui` so child output doesn't clobber the dashboard.
+macro_rules! run_forkserver_pipeline {
+    (
+        opt: $opt:expr,
+        shmem_provider: $shmem_provider:ident,
+        map_size: $map_size:expr,
+        edges_observer: $edges_observer:expr,
+        extra_objective_observers: $extra_observers:expr,
+        debug_child: $debug_child:expr,
+        colorization: $colorization:expr,
+        calibration: $calibration:expr,
+        generalization: $generalization:expr,
+        mutator: $mutator:expr,
+        grimoire_mutator: $grimoire_mutator:expr,
+        is_main_node: $is_main_node:expr,
+        fuzzer: $fuzzer:ident,
+        state: $state:ident,
+        mgr: $mgr:ident,
+    ) => {{
+        // The CmpLog map shared between the CmpLog observer and CmpLog executor
+        let mut cmplog_shmem = $shmem_provider.uninit_on_shmem::<AFLppCmpLogMap>().unwrap();
+        unsafe {
+            cmplog_shmem.write_to_env(SHM_CMPLOG_ENV_VAR).unwrap();
+        }
+        let cmpmap = unsafe { OwnedRefMut::from_shmem(&mut cmplog_shmem) };
+        let cmplog_observer = AFLppCmpLogObserver::new("cmplog", cmpmap, true);
+        let cmplog_ref = cmplog_observer.handle();
+        let cmplog_executor = ForkserverExecutor::builder()
+            .program($opt.executable.clone())
+            .coverage_map_size($map_size)
+            .is_persistent(true)
+            .is_deferred_frksrv(true)
+            .timeout(Duration::from_millis(($opt.hang_timeout * 1000) * 2))
+            .shmem_provider(&mut $shmem_provider)
+            .build(tuple_list!(cmplog_observer))
+            .unwrap();
+        let tracing = AFLppCmplogTracingStage::new(cmplog_executor, cmplog_ref);
+        let rq = MultiMutationalStage::<_, _, BytesInput, _, _, _>::new(
+            AFLppRedQueen::with_cmplog_options(true, true),
+        );
+        let cb = |_fuzzer: &mut _,
+                  _executor: &mut _,
+                  state: &mut StdState<_, _, _, _>,
+                  _event_manager: &mut _|
+         -> Result<bool, Error> {
+            let testcase = state.current_testcase()?;
+            if $is_main_node && testcase.scheduled_count() == 0 {
+                return Ok(true);
+            }
+            Ok(false)
+        };
+        let sync_stage = IfStage::new(
+            |_, _, _, _| Ok($is_main_node && !$opt.foreign_sync_dirs.is_empty()),
+            tuple_list!(SyncFromDiskStage::with_from_file(
+                $opt.foreign_sync_dirs.clone(),
+                Duration::from_secs(15 * 60),
+            )),
+        );
+        let cmplog = IfStage::new(cb, tuple_list!($colorization, tracing, rq));
+        let mut stages = tuple_list!(
+            $calibration,
+            cmplog,
+            $generalization,
+            StdMutationalStage::new($mutator),
+            StdMutationalStage::<_, _, GeneralizedInputMetadata, BytesInput, _, _, _>::transforming(
+                $grimoire_mutator
+            ),
+            sync_stage,
+        );
+        let mut executor = ForkserverExecutor::builder()
+            .program($opt.executable.clone())
+            .coverage_map_size($map_size)
+            .debug_child($debug_child)
+            .is_persistent(true)
+            .is_deferred_frksrv(true)
+            .timeout(Duration::from_millis($opt.hang_timeout * 1000))
+            .shmem_provider(&mut $shmem_provider)
+            .build_dynamic_map($edges_observer, $extra_observers)
+            .unwrap();
+        if $state.must_load_initial_inputs() {
+            $state.load_initial_inputs(
+                &mut $fuzzer,
+                &mut executor,
+                &mut $mgr,
+                &[$opt.out_dir.join("queue").clone(), $opt.input_dir.clone()],
+            )?;
+        }
+        $fuzzer.fuzz_loop(&mut stages, &mut executor, &mut $state, &mut $mgr)?;
+    }};
+}
+
 fn main() {
     let opt = Opt::parse();
     let shmem_provider = StdShMemProvider::new().expect("Failed to init shared memory");
-    let monitor = MultiMonitor::new(|s| println!("{s}"));
     match std::fs::create_dir(&opt.out_dir) {
         Ok(_) => {}
         Err(e) => {
@@ -52,27 +384,128 @@ fn main() {
     if !opt.out_dir.join("crashes").exists() {
         std::fs::create_dir(opt.out_dir.join("crashes")).unwrap();
     }
+    if let Some(Mode::Triage { file }) = opt.mode.clone() {
+        return run_triage(&opt, file).expect("triage run failed");
+    }
+    let hang_dir = opt
+        .hang_dir
+        .clone()
+        .unwrap_or_else(|| opt.out_dir.join("hangs"));
+    if !hang_dir.exists() {
+        std::fs::create_dir(&hang_dir).unwrap();
+    }
     let run_client = |mut state: Option<_>,
                       mut mgr: _,
                       core: ClientDescription|
      -> Result<(), libafl_bolts::Error> {
-        let map_size = {
-            let map_size = Command::new(opt.executable.clone())
-                .env("AFL_DUMP_MAP_SIZE", "1")
-                .output()
-                .expect("target gave no output");
-            let map_size = String::from_utf8(map_size.stdout)
-                .expect("target returned illegal mapsize")
-                .replace("\n", "");
-            map_size.parse::<usize>().expect("illegal mapsize output") + opt.map_bias
-        };
-        // Create the shared memory map for comms with the forkserver
-        let mut shmem_provider = UnixShMemProvider::new().unwrap();
-        let mut shmem = shmem_provider.new_shmem(map_size).unwrap();
         let is_main_node = opt.cores.position(core.core_id()).expect("invariant") == 0;
-        unsafe {
-            shmem.write_to_env(SHMEM_ENV_VAR).unwrap();
+        if opt.nyx {
+            // Nyx drives the target through a KVM snapshot instead of a forkserver,
+            // so there is no `AFL_DUMP_MAP_SIZE` probe and no shared-memory handshake:
+            // the helper hands us the coverage bitmap directly.
+            let share_dir = opt
+                .nyx_share_dir
+                .clone()
+                .expect("--nyx-share-dir is required when --nyx is set");
+            let parallel_mode = !is_main_node;
+            let mut nyx_helper = NyxHelperBuilder::default()
+                .build(share_dir, core.core_id().0 as u32, parallel_mode)
+                .expect("failed to initialize the Nyx snapshot helper");
+            let edges_observer = unsafe {
+                HitcountsMapObserver::new(StdMapObserver::from_mut_ptr(
+                    "edges",
+                    nyx_helper.bitmap_buffer,
+                    nyx_helper.bitmap_size,
+                ))
+                .track_indices()
+                .track_novelties()
+            };
+            let map_feedback = MaxMapFeedback::new(&edges_observer);
+            // Calibrate exec time/bitmap size/stability before the weighted scheduler uses it.
+            let calibration = CalibrationStage::new(&map_feedback);
+            let time_observer = TimeObserver::new("time");
+            let mut feedback = feedback_or!(map_feedback, TimeFeedback::new(&time_observer));
+            let hangs_corpus = OnDiskCorpus::<BytesInput>::new(hang_dir.clone()).unwrap();
+            let mut objective =
+                feedback_or_fast!(CrashFeedback::new(), HangArchiveFeedback::new(hangs_corpus));
+            let mut state = state.unwrap_or_else(|| {
+                StdState::new(
+                    StdRand::with_seed(current_nanos()),
+                    // TODO: configure testcache size
+                    CachedOnDiskCorpus::<BytesInput>::new(opt.out_dir.join("queue"), 1000).unwrap(),
+                    OnDiskCorpus::<BytesInput>::new(opt.out_dir.join("crashes")).unwrap(),
+                    &mut feedback,
+                    &mut objective,
+                )
+                .unwrap()
+            });
+            if let Some(dict) = opt.dict_path.clone() {
+                let mut tokens = Tokens::new();
+                tokens = tokens.add_from_files(vec![dict]).expect("tokens");
+                state.add_metadata(tokens);
+            }
+            let scheduler = StdWeightedScheduler::with_schedule(
+                &mut state,
+                &edges_observer,
+                Some(PowerSchedule::explore()),
+            );
+            let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
+
+            let generalization = GeneralizationStage::new(&edges_observer);
+            let mutator = HavocScheduledMutator::with_max_stack_pow(
+                havoc_mutations().merge(tokens_mutations()),
+                3,
+            );
+            let grimoire_mutator = HavocScheduledMutator::with_max_stack_pow(
+                tuple_list!(
+                    GrimoireExtensionMutator::new(),
+                    GrimoireRecursiveReplacementMutator::new(),
+                    GrimoireStringReplacementMutator::new(),
+                    GrimoireRandomDeleteMutator::new(),
+                ),
+                3,
+            );
+            // CmpLog/RedQueen trace comparison operands through a shared bitmap the
+            // forkserver writes into; Nyx snapshots don't expose an equivalent
+            // buffer, so CmpLog tracing and RedQueen simply aren't available under
+            // --nyx, with or without `nyx_helper.cmplog_enabled()`. Running a bare
+            // `ColorizationStage` with no tracing/RedQueen downstream to consume it
+            // would just burn cycles, so the whole stage is left out here.
+            println!("--nyx: CmpLog/RedQueen is not supported, running without it");
+            let sync_stage = IfStage::new(
+                |_, _, _, _| Ok(is_main_node && !opt.foreign_sync_dirs.is_empty()),
+                tuple_list!(SyncFromDiskStage::with_from_file(
+                    opt.foreign_sync_dirs.clone(),
+                    Duration::from_secs(15 * 60),
+                )),
+            );
+            let mut stages = tuple_list!(
+                calibration,
+                generalization,
+                StdMutationalStage::new(mutator),
+                StdMutationalStage::<_, _, GeneralizedInputMetadata, BytesInput, _, _, _>::transforming(
+                    grimoire_mutator
+                ),
+                sync_stage,
+            );
+            let mut executor = NyxExecutor::builder()
+                .timeout(Duration::from_millis(opt.hang_timeout * 1000))
+                .build(nyx_helper, tuple_list!(edges_observer, time_observer));
+            if state.must_load_initial_inputs() {
+                state.load_initial_inputs(
+                    &mut fuzzer,
+                    &mut executor,
+                    &mut mgr,
+                    &[opt.out_dir.join("queue").clone(), opt.input_dir],
+                )?;
+            }
+            fuzzer.fuzz_loop(&mut stages, &mut executor, &mut state, &mut mgr)?;
+            return Ok(());
         }
+        let map_size = probe_map_size(opt);
+        // Create the shared memory map for comms with the forkserver
+        let mut shmem_provider = UnixShMemProvider::new().unwrap();
+        let mut shmem = new_coverage_shmem(&mut shmem_provider, map_size);
         let shmem_buf = shmem.as_slice_mut();
         let edges_observer = unsafe {
             HitcountsMapObserver::new(StdMapObserver::new("edges", shmem_buf))
@@ -81,10 +514,87 @@ fn main() {
         };
         let colorization = ColorizationStage::new(&edges_observer);
         let map_feedback = MaxMapFeedback::new(&edges_observer);
+        // Calibrate exec time/bitmap size/stability before the weighted scheduler uses it.
+        let calibration = CalibrationStage::new(&map_feedback);
         // Create an observation channel to keep track of the execution time.
         let time_observer = TimeObserver::new("time");
         let mut feedback = feedback_or!(map_feedback, TimeFeedback::new(&time_observer));
-        let mut objective = CrashFeedback::new();
+        if opt.dedup_crashes {
+            // The backtrace observer parses the ASAN/LLVM sanitizer report from the
+            // child's stderr after a crash into an ordered list of stack frames.
+            // `CrashDedupFeedback` hashes the top frames and only reports the crash
+            // as interesting the first time that hash is seen, so the crash corpus
+            // ends up with one representative per unique call stack. A crash whose
+            // trace can't be parsed still falls through as interesting, so nothing
+            // is lost.
+            let backtrace_observer = AsanBacktraceObserver::default();
+            let hangs_corpus = OnDiskCorpus::<BytesInput>::new(hang_dir.clone()).unwrap();
+            let mut objective = feedback_or_fast!(
+                feedback_and_fast!(
+                    CrashFeedback::new(),
+                    CrashDedupFeedback::new(&backtrace_observer)
+                ),
+                HangArchiveFeedback::new(hangs_corpus)
+            );
+            let mut state = state.unwrap_or_else(|| {
+                StdState::new(
+                    StdRand::with_seed(current_nanos()),
+                    // TODO: configure testcache size
+                    CachedOnDiskCorpus::<BytesInput>::new(opt.out_dir.join("queue"), 1000).unwrap(),
+                    OnDiskCorpus::<BytesInput>::new(opt.out_dir.join("crashes")).unwrap(),
+                    &mut feedback,
+                    &mut objective,
+                )
+                .unwrap()
+            });
+            if let Some(dict) = opt.dict_path.clone() {
+                let mut tokens = Tokens::new();
+                tokens = tokens.add_from_files(vec![dict]).expect("tokens");
+                state.add_metadata(tokens);
+            }
+            let scheduler = StdWeightedScheduler::with_schedule(
+                &mut state,
+                &edges_observer,
+                Some(PowerSchedule::explore()),
+            );
+            let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
+
+            let generalization = GeneralizationStage::new(&edges_observer);
+            let mutator = HavocScheduledMutator::with_max_stack_pow(
+                havoc_mutations().merge(tokens_mutations()),
+                3,
+            );
+            let grimoire_mutator = HavocScheduledMutator::with_max_stack_pow(
+                tuple_list!(
+                    GrimoireExtensionMutator::new(),
+                    GrimoireRecursiveReplacementMutator::new(),
+                    GrimoireStringReplacementMutator::new(),
+                    GrimoireRandomDeleteMutator::new(),
+                ),
+                3,
+            );
+            run_forkserver_pipeline!(
+                opt: opt,
+                shmem_provider: shmem_provider,
+                map_size: map_size,
+                edges_observer: edges_observer,
+                extra_objective_observers: tuple_list!(time_observer, backtrace_observer),
+                debug_child: false,
+                colorization: colorization,
+                calibration: calibration,
+                generalization: generalization,
+                mutator: mutator,
+                grimoire_mutator: grimoire_mutator,
+                is_main_node: is_main_node,
+                fuzzer: fuzzer,
+                state: state,
+                mgr: mgr,
+            );
+            return Ok(());
+        }
+        let hangs_corpus = OnDiskCorpus::<BytesInput>::new(hang_dir.clone()).unwrap();
+        let mut objective =
+            feedback_or_fast!(CrashFeedback::new(), HangArchiveFeedback::new(hangs_corpus));
         // Initialize our State if necessary
         let mut state = state.unwrap_or_else(|| {
             StdState::new(
@@ -97,7 +607,7 @@ fn main() {
             )
             .unwrap()
         });
-        if let Some(dict) = opt.dict_path {
+        if let Some(dict) = opt.dict_path.clone() {
             let mut tokens = Tokens::new();
             tokens = tokens.add_from_files(vec![dict]).expect("tokens");
             state.add_metadata(tokens);
@@ -111,6 +621,48 @@ fn main() {
         let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
 
         let generalization = GeneralizationStage::new(&edges_observer);
+        if opt.mopt {
+            // `StdMOptMutator` self-tunes the havoc/token operator selection
+            // probabilities with particle-swarm optimization: each pilot window it
+            // tracks how many finds each operator produced, nudges every particle's
+            // velocity toward the globally best-performing distribution, and
+            // re-normalizes into sampling weights before the next pilot run. The
+            // Grimoire transforming stage is left untouched so grammar inference
+            // still runs on top of it.
+            let mopt_mutator = StdMOptMutator::new(
+                &mut state,
+                havoc_mutations().merge(tokens_mutations()),
+                7,
+                5,
+            )?;
+            let grimoire_mutator = HavocScheduledMutator::with_max_stack_pow(
+                tuple_list!(
+                    GrimoireExtensionMutator::new(),
+                    GrimoireRecursiveReplacementMutator::new(),
+                    GrimoireStringReplacementMutator::new(),
+                    GrimoireRandomDeleteMutator::new(),
+                ),
+                3,
+            );
+            run_forkserver_pipeline!(
+                opt: opt,
+                shmem_provider: shmem_provider,
+                map_size: map_size,
+                edges_observer: edges_observer,
+                extra_objective_observers: tuple_list!(time_observer),
+                debug_child: !opt.tui,
+                colorization: colorization,
+                calibration: calibration,
+                generalization: generalization,
+                mutator: mopt_mutator,
+                grimoire_mutator: grimoire_mutator,
+                is_main_node: is_main_node,
+                fuzzer: fuzzer,
+                state: state,
+                mgr: mgr,
+            );
+            return Ok(());
+        }
         // Setup a mutational stage with a basic bytes mutator
         let mutator = HavocScheduledMutator::with_max_stack_pow(
             havoc_mutations().merge(tokens_mutations()),
@@ -125,93 +677,52 @@ fn main() {
             ),
             3,
         );
-        // The CmpLog map shared between the CmpLog observer and CmpLog executor
-        let mut cmplog_shmem = shmem_provider.uninit_on_shmem::<AFLppCmpLogMap>().unwrap();
-
-        // Let the Forkserver know the CmpLog shared memory map ID.
-        unsafe {
-            cmplog_shmem.write_to_env(SHM_CMPLOG_ENV_VAR).unwrap();
-        }
-        let cmpmap = unsafe { OwnedRefMut::from_shmem(&mut cmplog_shmem) };
-
-        // Create the CmpLog observer.
-        let cmplog_observer = AFLppCmpLogObserver::new("cmplog", cmpmap, true);
-        let cmplog_ref = cmplog_observer.handle();
-        let mut cmplog_executor = ForkserverExecutor::builder()
-            .program(opt.executable.clone())
-            .coverage_map_size(map_size)
-            .is_persistent(true)
-            .is_deferred_frksrv(true)
-            .timeout(Duration::from_millis((opt.hang_timeout * 1000) * 2))
-            .shmem_provider(&mut shmem_provider)
-            .build(tuple_list!(cmplog_observer))
-            .unwrap();
-        // Create the CmpLog tracing stage.
-        let tracing = AFLppCmplogTracingStage::new(cmplog_executor, cmplog_ref);
-
-        // Create a randomic Input2State stage
-        let rq = MultiMutationalStage::<_, _, BytesInput, _, _, _>::new(
-            AFLppRedQueen::with_cmplog_options(true, true),
-        );
-        let cb = |_fuzzer: &mut _,
-                  _executor: &mut _,
-                  state: &mut StdState<_, _, _, _>,
-                  _event_manager: &mut _|
-         -> Result<bool, Error> {
-            let testcase = state.current_testcase()?;
-            if is_main_node && testcase.scheduled_count() == 0 {
-                return Ok(true);
-            }
-            Ok(false)
-        };
-        // Create a Sync stage to sync from foreign fuzzers
-        let sync_stage = IfStage::new(
-            |_, _, _, _| Ok(is_main_node && !opt.foreign_sync_dirs.is_empty()),
-            tuple_list!(SyncFromDiskStage::with_from_file(
-                opt.foreign_sync_dirs.clone(),
-                Duration::from_secs(15 * 60),
-            )),
-        );
-        let cmplog = IfStage::new(cb, tuple_list!(colorization, tracing, rq));
-        let mut stages = tuple_list!(
-            cmplog,
-            generalization,
-            StdMutationalStage::new(mutator),
-            StdMutationalStage::<_, _, GeneralizedInputMetadata, BytesInput, _, _, _>::transforming(
-                grimoire_mutator
-            ),
-            sync_stage,
+        run_forkserver_pipeline!(
+            opt: opt,
+            shmem_provider: shmem_provider,
+            map_size: map_size,
+            edges_observer: edges_observer,
+            extra_objective_observers: tuple_list!(time_observer),
+            debug_child: !opt.tui,
+            colorization: colorization,
+            calibration: calibration,
+            generalization: generalization,
+            mutator: mutator,
+            grimoire_mutator: grimoire_mutator,
+            is_main_node: is_main_node,
+            fuzzer: fuzzer,
+            state: state,
+            mgr: mgr,
         );
-        let mut executor = ForkserverExecutor::builder()
-            .program(opt.executable.clone())
-            .coverage_map_size(map_size)
-            .debug_child(true)
-            .is_persistent(true)
-            .is_deferred_frksrv(true)
-            .timeout(Duration::from_millis(opt.hang_timeout * 1000))
-            .shmem_provider(&mut shmem_provider)
-            .build_dynamic_map(edges_observer, tuple_list!(time_observer))
-            .unwrap();
-        if state.must_load_initial_inputs() {
-            state.load_initial_inputs(
-                &mut fuzzer,
-                &mut executor,
-                &mut mgr,
-                &[opt.out_dir.join("queue").clone(), opt.input_dir],
-            )?;
-        }
-        fuzzer.fuzz_loop(&mut stages, &mut executor, &mut state, &mut mgr)?;
         Ok(())
     };
-    let _res = Launcher::builder()
-        .cores(&opt.cores)
-        .monitor(monitor)
-        .run_client(run_client)
-        .broker_port(opt.broker_port)
-        .shmem_provider(shmem_provider)
-        .configuration(EventConfig::from_name("default"))
-        .build()
-        .launch();
+    // `--tui` trades the scrolling println! log (handy for scraping) for a
+    // full-screen dashboard of per-client exec/s, corpus size, crash/hang
+    // counts, map density and power-schedule stats that's easier to watch
+    // across many cores.
+    if opt.tui {
+        let monitor = TuiMonitor::builder().title("grimoire").build();
+        let _res = Launcher::builder()
+            .cores(&opt.cores)
+            .monitor(monitor)
+            .run_client(run_client)
+            .broker_port(opt.broker_port)
+            .shmem_provider(shmem_provider)
+            .configuration(EventConfig::from_name("default"))
+            .build()
+            .launch();
+    } else {
+        let monitor = MultiMonitor::new(|s| println!("{s}"));
+        let _res = Launcher::builder()
+            .cores(&opt.cores)
+            .monitor(monitor)
+            .run_client(run_client)
+            .broker_port(opt.broker_port)
+            .shmem_provider(shmem_provider)
+            .configuration(EventConfig::from_name("default"))
+            .build()
+            .launch();
+    }
 }
 #[derive(Debug, Parser, Clone)]
 #[command(
@@ -241,5 +752,33 @@ struct Opt {
     map_bias: usize,
     #[arg(short = 'F')]
     foreign_sync_dirs: Vec<PathBuf>,
+    /// Deduplicate crashes by stack-trace hash instead of saving every crash
+    #[arg(long)]
+    dedup_crashes: bool,
+    /// Directory for timed-out inputs, defaults to out_dir/hangs
+    #[arg(long)]
+    hang_dir: Option<PathBuf>,
+    /// Drive the target through a Nyx KVM snapshot instead of the forkserver
+    #[arg(long)]
+    nyx: bool,
+    /// Shared directory for the Nyx snapshot, required with --nyx
+    #[arg(long)]
+    nyx_share_dir: Option<PathBuf>,
+    /// Render a full-screen TUI dashboard instead of scrolling println! logs
+    #[arg(long)]
+    tui: bool,
+    /// Self-tune havoc/token mutator weights with MOpt instead of a fixed stack
+    #[arg(long)]
+    mopt: bool,
+    #[command(subcommand)]
+    mode: Option<Mode>,
+}
+#[derive(Debug, Subcommand, Clone)]
+enum Mode {
+    /// Replay a saved crash through a single executor, outside the fuzzing loop
+    Triage {
+        /// Path to the input to replay
+        file: PathBuf,
+    },
 }
 pub const SHM_CMPLOG_ENV_VAR: &str = "__AFL_CMPLOG_SHM_ID";